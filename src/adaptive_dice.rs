@@ -0,0 +1,159 @@
+//   Copyright GFX Developers 2014-2017
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Dicing parametric surfaces to a target edge length instead of a fixed
+//! subdivision count, the way an offline renderer dices patches on
+//! demand. `Cylinder::new`/`Cylinder::subdivide` force every user to
+//! pick one uniform `sub_u`/`sub_h`; `AdaptiveDice` instead estimates the
+//! edge lengths a patch needs and emits just enough geometry to keep
+//! them under the target, so small cylinders aren't over-tessellated
+//! and large ones aren't under-tessellated.
+
+use std::f32::consts::PI;
+use Vertex;
+use super::{Polygon, Triangle, Quad, Cylinder};
+
+/// Refines a parametric surface to a target edge length rather than a
+/// fixed subdivision count.
+///
+/// `target_edge_length` is measured in the same units as the surface's
+/// own geometry (world space); passing a projected length computed from
+/// a view-projection matrix gives screen-space-driven dicing instead.
+pub trait AdaptiveDice {
+    /// Dices the surface so no edge is longer than `target_edge_length`,
+    /// fanning any transition between a patch's differing split factors
+    /// (e.g. a cap's centre point against its innermost ring) so there
+    /// are no T-junctions between differently-diced neighbours.
+    fn adaptive_dice(&self, target_edge_length: f32) -> Vec<Polygon<Vertex>>;
+}
+
+/// One horizontal ring of vertices around the side wall, evenly spaced
+/// in the parametric `u` direction, with the normal pointing straight
+/// out from the axis.
+fn ring(z: f32, radius: f32, count: usize) -> Vec<Vertex> {
+    (0..count)
+        .map(|i| {
+            let a = i as f32 / count as f32 * PI * 2.;
+            let (c, s) = (a.cos(), a.sin());
+            Vertex {
+                pos: [c * radius, s * radius, z],
+                normal: [c, s, 0.],
+                uv: [i as f32 / count as f32, (z + 1.) / 2.],
+            }
+        })
+        .collect()
+}
+
+/// One concentric ring on a flat end cap, evenly spaced in `u` at a
+/// given fraction of the cap's full radius, with the normal pointing
+/// straight along the axis.
+fn cap_ring(z: f32, radius: f32, normal_z: f32, count: usize) -> Vec<Vertex> {
+    (0..count)
+        .map(|i| {
+            let a = i as f32 / count as f32 * PI * 2.;
+            let (c, s) = (a.cos(), a.sin());
+            Vertex {
+                pos: [c * radius, s * radius, z],
+                normal: [0., 0., normal_z],
+                uv: [0.5 + c * radius * 0.5, 0.5 + s * radius * 0.5],
+            }
+        })
+        .collect()
+}
+
+/// Fans triangles from a single point out to every vertex of `ring`,
+/// the correct way to close off a ring down to a point (e.g. a cap's
+/// innermost concentric ring shrinking to the axis). `normal_z` picks
+/// the winding that makes the fan face the same way as `ring`'s own
+/// stored normals.
+fn fan(center: Vertex, ring: &[Vertex], normal_z: f32) -> Vec<Polygon<Vertex>> {
+    let n = ring.len();
+    (0..n)
+        .map(|i| if normal_z < 0. {
+                 Polygon::PolyTri(Triangle::new(center, ring[(i + 1) % n], ring[i]))
+             } else {
+                 Polygon::PolyTri(Triangle::new(center, ring[i], ring[(i + 1) % n]))
+             })
+        .collect()
+}
+
+/// Builds one flat end cap out of `r_count` concentric rings (plus the
+/// centre point), so its radial spokes stay under `target_edge_length`
+/// even though the side wall's own split factor is computed separately.
+fn cap(z: f32, radius: f32, normal_z: f32, u_count: usize, r_count: usize) -> Vec<Polygon<Vertex>> {
+    let center = Vertex {
+        pos: [0., 0., z],
+        normal: [0., 0., normal_z],
+        uv: [0.5, 0.5],
+    };
+
+    let rings: Vec<Vec<Vertex>> = (1..=r_count)
+        .map(|r| cap_ring(z, r as f32 / r_count as f32 * radius, normal_z, u_count))
+        .collect();
+
+    let mut polys = fan(center, &rings[0], normal_z);
+    for window in rings.windows(2) {
+        for i in 0..u_count {
+            let b0 = window[0][i];
+            let b1 = window[0][(i + 1) % u_count];
+            let t0 = window[1][i];
+            let t1 = window[1][(i + 1) % u_count];
+            // The bottom cap's rings are wound the opposite way from the
+            // top's so both faces point outward.
+            if normal_z < 0. {
+                polys.push(Polygon::PolyQuad(Quad::new(b0, b1, t1, t0)));
+            } else {
+                polys.push(Polygon::PolyQuad(Quad::new(b0, t0, t1, b1)));
+            }
+        }
+    }
+    polys
+}
+
+impl AdaptiveDice for Cylinder {
+    fn adaptive_dice(&self, target_edge_length: f32) -> Vec<Polygon<Vertex>> {
+        // `Cylinder` is always radius 1, height 2 (see its doc comment);
+        // each patch's split factor is `ceil(edge_length / target)` for
+        // that patch's own characteristic edge, so the side wall and the
+        // two caps are free to tessellate at different rates.
+        let radius = 1.;
+        let height = 2.;
+
+        let u_count = (2. * PI * radius / target_edge_length).ceil().max(3.) as usize;
+        let h_count = (height / target_edge_length).ceil().max(1.) as usize;
+        let r_count = (radius / target_edge_length).ceil().max(1.) as usize;
+
+        let side_rings: Vec<Vec<Vertex>> = (0..=h_count)
+            .map(|h| {
+                let z = (h as f32 / h_count as f32) * height - 1.;
+                ring(z, radius, u_count)
+            })
+            .collect();
+
+        let mut polys = cap(-1., radius, -1., u_count, r_count);
+
+        for window in side_rings.windows(2) {
+            for i in 0..u_count {
+                let b0 = window[0][i];
+                let b1 = window[0][(i + 1) % u_count];
+                let t0 = window[1][i];
+                let t1 = window[1][(i + 1) % u_count];
+                polys.push(Polygon::PolyQuad(Quad::new(b0, b1, t1, t0)));
+            }
+        }
+
+        polys.extend(cap(1., radius, 1., u_count, r_count));
+        polys
+    }
+}
@@ -0,0 +1,431 @@
+//   Copyright GFX Developers 2014-2017
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Binary space partitioning over streams of `Polygon<Vertex>`.
+//!
+//! A `BspTree` splits any polygon soup along planes drawn from the
+//! polygons themselves, so straddling polygons are cut in two and the
+//! tree can later be walked front-to-back (or back-to-front) relative to
+//! an arbitrary eye position. This is the groundwork the `csg` module
+//! builds on, but it is also useful on its own for correctly ordering
+//! transparent geometry.
+
+use {Normal, Position, Vertex};
+use super::{Polygon, Triangle, Quad};
+
+/// Distances below this magnitude are treated as "on the plane" rather
+/// than strictly in front of or behind it.
+const EPSILON: f32 = 1e-5;
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = dot(a, a).sqrt();
+    if len > 0. { [a[0] / len, a[1] / len, a[2] / len] } else { a }
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+fn lerp2(a: [f32; 2], b: [f32; 2], t: f32) -> [f32; 2] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+}
+
+/// An infinite plane, defined by a unit normal and a point that lies on it.
+#[derive(Copy, Clone)]
+pub struct Plane {
+    normal: Normal,
+    point: Position,
+}
+
+impl Plane {
+    /// Builds the plane a polygon lies in, from its first three
+    /// vertices: the normal is their geometric cross product rather than
+    /// any vertex's stored `normal`, so this stays correct for smooth-
+    /// shaded input (e.g. the output of `Subdivide`) whose per-vertex
+    /// normals don't match the polygon's true face plane.
+    pub fn from_polygon(poly: &Polygon<Vertex>) -> Self {
+        let verts = corners(poly);
+        let normal = normalize(cross(sub(verts[1].pos, verts[0].pos), sub(verts[2].pos, verts[1].pos)));
+        Plane {
+            normal,
+            point: verts[0].pos,
+        }
+    }
+
+    /// Signed distance from `p` to the plane; positive is in front.
+    pub fn distance(&self, p: Position) -> f32 {
+        dot(self.normal, sub(p, self.point))
+    }
+}
+
+/// Where a vertex (or whole polygon) falls relative to a splitting plane.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Side {
+    Front,
+    Back,
+    Coplanar,
+}
+
+fn classify(plane: &Plane, p: Position) -> Side {
+    let d = plane.distance(p);
+    if d > EPSILON {
+        Side::Front
+    } else if d < -EPSILON {
+        Side::Back
+    } else {
+        Side::Coplanar
+    }
+}
+
+fn corners(poly: &Polygon<Vertex>) -> Vec<Vertex> {
+    match *poly {
+        Polygon::PolyTri(t) => vec![t.x, t.y, t.z],
+        Polygon::PolyQuad(q) => vec![q.x, q.y, q.z, q.w],
+    }
+}
+
+/// Turns a (possibly clipped) vertex loop back into one or more polygons,
+/// fan-triangulating anything wider than a quad.
+fn to_polygons(verts: Vec<Vertex>) -> Vec<Polygon<Vertex>> {
+    match verts.len() {
+        0 | 1 | 2 => vec![],
+        3 => vec![Polygon::PolyTri(Triangle::new(verts[0], verts[1], verts[2]))],
+        4 => vec![Polygon::PolyQuad(Quad::new(verts[0], verts[1], verts[2], verts[3]))],
+        _ => {
+            (1..verts.len() - 1)
+                .map(|i| Polygon::PolyTri(Triangle::new(verts[0], verts[i], verts[i + 1])))
+                .collect()
+        }
+    }
+}
+
+fn negate(n: Normal) -> Normal {
+    [-n[0], -n[1], -n[2]]
+}
+
+fn flip_vertex(v: Vertex) -> Vertex {
+    Vertex {
+        pos: v.pos,
+        normal: negate(v.normal),
+        uv: v.uv,
+    }
+}
+
+/// Reverses a polygon's winding and flips its normals, so it points the
+/// opposite way. Used when a CSG operand's solid is complemented.
+fn flip_polygon(poly: &Polygon<Vertex>) -> Polygon<Vertex> {
+    match *poly {
+        Polygon::PolyTri(t) => {
+            Polygon::PolyTri(Triangle::new(flip_vertex(t.z), flip_vertex(t.y), flip_vertex(t.x)))
+        }
+        Polygon::PolyQuad(q) => {
+            Polygon::PolyQuad(Quad::new(flip_vertex(q.w),
+                                         flip_vertex(q.z),
+                                         flip_vertex(q.y),
+                                         flip_vertex(q.x)))
+        }
+    }
+}
+
+fn interpolate(a: Vertex, b: Vertex, t: f32) -> Vertex {
+    Vertex {
+        pos: lerp(a.pos, b.pos, t),
+        normal: lerp(a.normal, b.normal, t),
+        uv: lerp2(a.uv, b.uv, t),
+    }
+}
+
+/// Splits a polygon against `plane`, pushing the pieces that fall in
+/// front into `front` and the pieces that fall behind into `back`.
+/// Coplanar polygons are returned separately so callers can attach them
+/// to the splitting node instead of recursing on them.
+fn split_polygon(plane: &Plane,
+                  poly: &Polygon<Vertex>,
+                  front: &mut Vec<Polygon<Vertex>>,
+                  back: &mut Vec<Polygon<Vertex>>,
+                  coplanar: &mut Vec<Polygon<Vertex>>) {
+    let verts = corners(poly);
+    let sides: Vec<Side> = verts.iter().map(|v| classify(plane, v.pos)).collect();
+
+    if sides.iter().all(|s| *s != Side::Back) {
+        if sides.iter().all(|s| *s == Side::Coplanar) {
+            coplanar.push(poly.clone());
+        } else {
+            front.push(poly.clone());
+        }
+        return;
+    }
+    if sides.iter().all(|s| *s != Side::Front) {
+        back.push(poly.clone());
+        return;
+    }
+
+    // The polygon straddles the plane: walk its edges, keeping each
+    // vertex on the loop for its side (coplanar vertices on both) and
+    // inserting a new interpolated vertex on both loops wherever an
+    // edge crosses the plane.
+    let n = verts.len();
+    let mut front_loop = Vec::with_capacity(n + 1);
+    let mut back_loop = Vec::with_capacity(n + 1);
+
+    for i in 0..n {
+        let cur = verts[i];
+        let cur_side = sides[i];
+        let next = verts[(i + 1) % n];
+        let next_side = sides[(i + 1) % n];
+
+        if cur_side != Side::Back {
+            front_loop.push(cur);
+        }
+        if cur_side != Side::Front {
+            back_loop.push(cur);
+        }
+
+        let crosses = (cur_side == Side::Front && next_side == Side::Back) ||
+            (cur_side == Side::Back && next_side == Side::Front);
+        if crosses {
+            let t = plane.distance(cur.pos) / (plane.distance(cur.pos) - plane.distance(next.pos));
+            let mid = interpolate(cur, next, t);
+            front_loop.push(mid);
+            back_loop.push(mid);
+        }
+    }
+
+    front.extend(to_polygons(front_loop));
+    back.extend(to_polygons(back_loop));
+}
+
+/// A single node of the tree: a splitting plane, the polygons that lie
+/// exactly in it, and the front/back subtrees.
+struct BspNode {
+    plane: Plane,
+    coplanar: Vec<Polygon<Vertex>>,
+    front: Option<Box<BspNode>>,
+    back: Option<Box<BspNode>>,
+}
+
+impl BspNode {
+    fn build(polygons: Vec<Polygon<Vertex>>) -> Option<Box<BspNode>> {
+        let mut iter = polygons.into_iter();
+        let first = iter.next()?;
+        let plane = Plane::from_polygon(&first);
+
+        let mut coplanar = vec![first];
+        let mut front_polys = Vec::new();
+        let mut back_polys = Vec::new();
+
+        for poly in iter {
+            split_polygon(&plane, &poly, &mut front_polys, &mut back_polys, &mut coplanar);
+        }
+
+        Some(Box::new(BspNode {
+            plane,
+            coplanar,
+            front: BspNode::build(front_polys),
+            back: BspNode::build(back_polys),
+        }))
+    }
+
+    fn ordered_into(&self, eye: Position, out: &mut Vec<Polygon<Vertex>>) {
+        let near_side = classify(&self.plane, eye);
+        let (near, far) = if near_side == Side::Back {
+            (&self.back, &self.front)
+        } else {
+            (&self.front, &self.back)
+        };
+
+        if let Some(ref node) = *far {
+            node.ordered_into(eye, out);
+        }
+        out.extend(self.coplanar.iter().cloned());
+        if let Some(ref node) = *near {
+            node.ordered_into(eye, out);
+        }
+    }
+
+    /// Merges more polygons into this subtree in place, the way `build`
+    /// would have if they had been included from the start.
+    fn insert(&mut self, polygons: Vec<Polygon<Vertex>>) {
+        let mut front_polys = Vec::new();
+        let mut back_polys = Vec::new();
+
+        for poly in polygons {
+            split_polygon(&self.plane, &poly, &mut front_polys, &mut back_polys, &mut self.coplanar);
+        }
+
+        match self.front {
+            Some(ref mut node) => node.insert(front_polys),
+            None => self.front = BspNode::build(front_polys),
+        }
+        match self.back {
+            Some(ref mut node) => node.insert(back_polys),
+            None => self.back = BspNode::build(back_polys),
+        }
+    }
+
+    /// Flips the whole subtree: every plane and polygon is reversed and
+    /// the front/back children swap places, turning "outside" into
+    /// "inside" and vice versa.
+    fn invert(&mut self) {
+        self.plane.normal = negate(self.plane.normal);
+        for poly in &mut self.coplanar {
+            *poly = flip_polygon(poly);
+        }
+        if let Some(ref mut node) = self.front {
+            node.invert();
+        }
+        if let Some(ref mut node) = self.back {
+            node.invert();
+        }
+        ::std::mem::swap(&mut self.front, &mut self.back);
+    }
+
+    /// Removes the parts of `polygons` that lie inside this subtree,
+    /// returning only the parts that are outside of it.
+    fn clip_polygons(&self, polygons: Vec<Polygon<Vertex>>) -> Vec<Polygon<Vertex>> {
+        let mut front_polys = Vec::new();
+        let mut back_polys = Vec::new();
+        let mut coplanar = Vec::new();
+
+        for poly in &polygons {
+            split_polygon(&self.plane, poly, &mut front_polys, &mut back_polys, &mut coplanar);
+        }
+        // A coplanar fragment's own normal can point either way relative
+        // to this node's plane (e.g. two operands with a flush face), so
+        // route it by that normal instead of assuming it agrees with the
+        // splitting polygon.
+        for poly in coplanar {
+            if dot(self.plane.normal, corners(&poly)[0].normal) >= 0. {
+                front_polys.push(poly);
+            } else {
+                back_polys.push(poly);
+            }
+        }
+
+        let front_polys = match self.front {
+            Some(ref node) => node.clip_polygons(front_polys),
+            None => front_polys,
+        };
+        let back_polys = match self.back {
+            // A leaf behind this plane with no further subtree is
+            // considered solid interior, so its fragments are dropped.
+            Some(ref node) => node.clip_polygons(back_polys),
+            None => Vec::new(),
+        };
+
+        let mut out = front_polys;
+        out.extend(back_polys);
+        out
+    }
+
+    /// Clips every polygon stored in this subtree against `other`,
+    /// discarding whatever falls inside it.
+    fn clip_to(&mut self, other: &BspNode) {
+        self.coplanar = other.clip_polygons(self.coplanar.clone());
+        if let Some(ref mut node) = self.front {
+            node.clip_to(other);
+        }
+        if let Some(ref mut node) = self.back {
+            node.clip_to(other);
+        }
+    }
+
+    fn all_polygons(&self, out: &mut Vec<Polygon<Vertex>>) {
+        out.extend(self.coplanar.iter().cloned());
+        if let Some(ref node) = self.front {
+            node.all_polygons(out);
+        }
+        if let Some(ref node) = self.back {
+            node.all_polygons(out);
+        }
+    }
+}
+
+/// A binary space partition tree built from a polygon soup.
+///
+/// Build one with `BspTree::from_polygons`, then call `ordered` to walk
+/// the polygons back-to-front (painter's algorithm) relative to any eye
+/// position, which is exactly what sorting transparent geometry needs.
+pub struct BspTree {
+    root: Option<Box<BspNode>>,
+}
+
+impl BspTree {
+    /// Builds a tree from any stream of polygons, splitting polygons
+    /// that straddle a chosen plane so every leaf ends up strictly on
+    /// one side of its ancestors' planes.
+    pub fn from_polygons<I: IntoIterator<Item = Polygon<Vertex>>>(polygons: I) -> Self {
+        BspTree { root: BspNode::build(polygons.into_iter().collect()) }
+    }
+
+    /// Returns the tree's polygons ordered back-to-front as seen from
+    /// `eye`, with straddling polygons already split along the planes
+    /// used to build the tree.
+    pub fn ordered(&self, eye: Position) -> ::std::vec::IntoIter<Polygon<Vertex>> {
+        let mut out = Vec::new();
+        if let Some(ref node) = self.root {
+            node.ordered_into(eye, &mut out);
+        }
+        out.into_iter()
+    }
+
+    /// Flips the tree in place, turning its solid's inside out.
+    pub(crate) fn invert(&mut self) {
+        if let Some(ref mut node) = self.root {
+            node.invert();
+        }
+    }
+
+    /// Discards every part of this tree's polygons that lies inside `other`.
+    pub(crate) fn clip_to(&mut self, other: &BspTree) {
+        if let (&mut Some(ref mut node), &Some(ref other_node)) = (&mut self.root, &other.root) {
+            node.clip_to(other_node);
+        }
+    }
+
+    /// Adds more polygons into this tree in place.
+    pub(crate) fn insert(&mut self, polygons: Vec<Polygon<Vertex>>) {
+        match self.root {
+            Some(ref mut node) => node.insert(polygons),
+            None => self.root = BspNode::build(polygons),
+        }
+    }
+
+    /// Collects every polygon currently stored in the tree.
+    pub(crate) fn all_polygons(&self) -> Vec<Polygon<Vertex>> {
+        let mut out = Vec::new();
+        if let Some(ref node) = self.root {
+            node.all_polygons(&mut out);
+        }
+        out
+    }
+}
+
+impl Clone for BspTree {
+    fn clone(&self) -> Self {
+        BspTree::from_polygons(self.all_polygons())
+    }
+}
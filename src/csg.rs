@@ -0,0 +1,84 @@
+//   Copyright GFX Developers 2014-2017
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Constructive solid geometry: `union`, `intersection` and `difference`
+//! between two closed polygon meshes, built entirely on top of the
+//! `bsp` module. Each operand is turned into a `BspTree`, clipped
+//! against the other's tree to drop the fragments that fall inside it,
+//! and the remaining fragments are recombined per the usual De Morgan
+//! identities.
+
+use Vertex;
+use super::Polygon;
+use super::bsp::BspTree;
+
+/// Combines two polygon soups, keeping the geometry that lies outside
+/// both solids: `a` with `b` clipped out of it, plus `b` with `a`
+/// clipped out of it.
+pub fn union<A, B>(a: A, b: B) -> Vec<Polygon<Vertex>>
+    where A: IntoIterator<Item = Polygon<Vertex>>,
+          B: IntoIterator<Item = Polygon<Vertex>>
+{
+    let mut a = BspTree::from_polygons(a);
+    let mut b = BspTree::from_polygons(b);
+
+    a.clip_to(&b);
+    b.clip_to(&a);
+    b.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.insert(b.all_polygons());
+    a.all_polygons()
+}
+
+/// Combines two polygon soups, keeping only the geometry that lies
+/// inside both solids.
+pub fn intersection<A, B>(a: A, b: B) -> Vec<Polygon<Vertex>>
+    where A: IntoIterator<Item = Polygon<Vertex>>,
+          B: IntoIterator<Item = Polygon<Vertex>>
+{
+    let mut a = BspTree::from_polygons(a);
+    let mut b = BspTree::from_polygons(b);
+
+    a.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.clip_to(&b);
+    b.clip_to(&a);
+    a.insert(b.all_polygons());
+    a.invert();
+    a.all_polygons()
+}
+
+/// Subtracts `b` from `a`: keeps the part of `a` that lies outside `b`,
+/// plus the part of `b`'s boundary that lies inside `a` (flipped so the
+/// new surface faces outward), producing e.g. a cube with a cylindrical
+/// hole through it.
+pub fn difference<A, B>(a: A, b: B) -> Vec<Polygon<Vertex>>
+    where A: IntoIterator<Item = Polygon<Vertex>>,
+          B: IntoIterator<Item = Polygon<Vertex>>
+{
+    let mut a = BspTree::from_polygons(a);
+    let mut b = BspTree::from_polygons(b);
+
+    a.invert();
+    a.clip_to(&b);
+    b.clip_to(&a);
+    b.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.insert(b.all_polygons());
+    a.invert();
+    a.all_polygons()
+}
@@ -0,0 +1,279 @@
+//   Copyright GFX Developers 2014-2017
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use Vertex;
+use super::{Quad, Polygon};
+use super::generators::{SharedVertex, IndexedPolygon};
+
+/// An undirected edge, always stored with the smaller index first so two
+/// faces that share an edge agree on its identity regardless of winding.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct Edge(usize, usize);
+
+impl Edge {
+    fn new(a: usize, b: usize) -> Self {
+        if a < b { Edge(a, b) } else { Edge(b, a) }
+    }
+}
+
+/// Pulls the corner indices out of whatever polygon type a generator's
+/// `IndexedPolygon` implementation hands back, so `Subdivide` can work
+/// with quad-only generators (`Cube`) and mixed tri/quad ones (`Cylinder`)
+/// alike.
+pub trait Corners {
+    fn corners(&self) -> Vec<usize>;
+}
+
+impl Corners for Quad<usize> {
+    fn corners(&self) -> Vec<usize> {
+        vec![self.x, self.y, self.z, self.w]
+    }
+}
+
+impl Corners for Polygon<usize> {
+    fn corners(&self) -> Vec<usize> {
+        match *self {
+            Polygon::PolyTri(t) => vec![t.x, t.y, t.z],
+            Polygon::PolyQuad(q) => vec![q.x, q.y, q.z, q.w],
+        }
+    }
+}
+
+fn average<I: Iterator<Item = Vertex>>(iter: I) -> Vertex {
+    let mut pos = [0.; 3];
+    let mut normal = [0.; 3];
+    let mut uv = [0.; 2];
+    let mut count = 0.;
+
+    for v in iter {
+        for i in 0..3 {
+            pos[i] += v.pos[i];
+            normal[i] += v.normal[i];
+        }
+        for i in 0..2 {
+            uv[i] += v.uv[i];
+        }
+        count += 1.;
+    }
+
+    for i in 0..3 {
+        pos[i] /= count;
+        normal[i] /= count;
+    }
+    for i in 0..2 {
+        uv[i] /= count;
+    }
+
+    Vertex { pos, normal, uv }
+}
+
+/// Moves an original vertex `p` of valence `n` to `(f + 2*r + (n-3)*p) / n`,
+/// the standard Catmull-Clark vertex rule, applied component-wise to
+/// `pos`, `normal` and `uv` so the result stays a plain `Vertex`.
+fn reposition(f: Vertex, r: Vertex, p: Vertex, n: f32) -> Vertex {
+    let mut pos = [0.; 3];
+    let mut normal = [0.; 3];
+    let mut uv = [0.; 2];
+
+    for i in 0..3 {
+        pos[i] = (f.pos[i] + 2. * r.pos[i] + (n - 3.) * p.pos[i]) / n;
+        normal[i] = (f.normal[i] + 2. * r.normal[i] + (n - 3.) * p.normal[i]) / n;
+    }
+    for i in 0..2 {
+        uv[i] = (f.uv[i] + 2. * r.uv[i] + (n - 3.) * p.uv[i]) / n;
+    }
+
+    Vertex { pos, normal, uv }
+}
+
+/// Quantized position, used as a hash key to weld coincident vertices.
+fn pos_key(p: [f32; 3]) -> (i64, i64, i64) {
+    const SCALE: f32 = 1_000_000.;
+    ((p[0] * SCALE).round() as i64, (p[1] * SCALE).round() as i64, (p[2] * SCALE).round() as i64)
+}
+
+/// Merges corners that sit at the same position into a single vertex
+/// before adjacency is built.
+///
+/// Some generators (`Cube`, for one) give every face its own disjoint
+/// block of indices even where faces meet at a shared corner, so the
+/// index graph alone understates a vertex's true valence; welding by
+/// position first is what lets Catmull-Clark see the real mesh.
+fn weld(verts: &[Vertex], faces: &[Vec<usize>]) -> (Vec<Vertex>, Vec<Vec<usize>>) {
+    let mut weld_id: HashMap<(i64, i64, i64), usize> = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut corner_to_weld = vec![0; verts.len()];
+
+    for (i, v) in verts.iter().enumerate() {
+        let key = pos_key(v.pos);
+        let id = *weld_id.entry(key).or_insert_with(|| {
+                                         groups.push(Vec::new());
+                                         groups.len() - 1
+                                     });
+        groups[id].push(i);
+        corner_to_weld[i] = id;
+    }
+
+    let welded_verts = groups
+        .iter()
+        .map(|g| average(g.iter().map(|&i| verts[i])))
+        .collect();
+    let welded_faces = faces
+        .iter()
+        .map(|f| f.iter().map(|&c| corner_to_weld[c]).collect())
+        .collect();
+
+    (welded_verts, welded_faces)
+}
+
+/// Runs a single level of Catmull-Clark subdivision over a polygon soup,
+/// returning the refined vertex buffer and its all-quad face list.
+fn catmull_clark(verts: &[Vertex], faces: &[Vec<usize>]) -> (Vec<Vertex>, Vec<Quad<usize>>) {
+    let face_points: Vec<Vertex> = faces
+        .iter()
+        .map(|f| average(f.iter().map(|&i| verts[i])))
+        .collect();
+
+    let mut edge_faces: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (fi, f) in faces.iter().enumerate() {
+        let n = f.len();
+        for k in 0..n {
+            let edge = Edge::new(f[k], f[(k + 1) % n]);
+            edge_faces.entry(edge).or_insert_with(Vec::new).push(fi);
+        }
+    }
+
+    let edge_points: HashMap<Edge, Vertex> = edge_faces
+        .iter()
+        .map(|(&edge, adjacent)| {
+            let a = verts[edge.0];
+            let b = verts[edge.1];
+            let point = match adjacent.len() {
+                2 => average([a, b, face_points[adjacent[0]], face_points[adjacent[1]]]
+                                 .iter()
+                                 .cloned()),
+                // boundary edge: no second face, fall back to the midpoint
+                _ => average([a, b].iter().cloned()),
+            };
+            (edge, point)
+        })
+        .collect();
+
+    let mut vert_faces: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut vert_edges: HashMap<usize, HashSet<Edge>> = HashMap::new();
+    for (fi, f) in faces.iter().enumerate() {
+        let n = f.len();
+        for k in 0..n {
+            let a = f[k];
+            let b = f[(k + 1) % n];
+            let edge = Edge::new(a, b);
+            vert_faces.entry(a).or_insert_with(Vec::new).push(fi);
+            // A vertex's two edges on the same face would otherwise be
+            // pushed twice (once from each side), which only cancels out
+            // for interior vertices where every edge is double-counted
+            // uniformly; de-duping keeps boundary vertices' `R` average
+            // correct too.
+            vert_edges.entry(a).or_insert_with(HashSet::new).insert(edge);
+            vert_edges.entry(b).or_insert_with(HashSet::new).insert(edge);
+        }
+    }
+
+    let mut new_verts: Vec<Vertex> = (0..verts.len())
+        .map(|i| {
+            let p = verts[i];
+            match (vert_faces.get(&i), vert_edges.get(&i)) {
+                (Some(adj_faces), Some(adj_edges)) if !adj_faces.is_empty() => {
+                    let f = average(adj_faces.iter().map(|&fi| face_points[fi]));
+                    // `R` is the average of the adjacent edges' midpoints,
+                    // not of their (face-weighted) edge points.
+                    let r = average(adj_edges
+                                        .iter()
+                                        .map(|e| average([verts[e.0], verts[e.1]].iter().cloned())));
+                    reposition(f, r, p, adj_faces.len() as f32)
+                }
+                _ => p,
+            }
+        })
+        .collect();
+
+    let face_offset = new_verts.len();
+    new_verts.extend(face_points.iter().cloned());
+
+    let edge_offset = new_verts.len();
+    let mut edge_index: HashMap<Edge, usize> = HashMap::new();
+    for (edge, point) in edge_points {
+        edge_index.insert(edge, edge_offset + edge_index.len());
+        new_verts.push(point);
+    }
+
+    let mut out_faces = Vec::with_capacity(faces.iter().map(|f| f.len()).sum());
+    for (fi, f) in faces.iter().enumerate() {
+        let n = f.len();
+        for k in 0..n {
+            let prev = f[(k + n - 1) % n];
+            let cur = f[k];
+            let next = f[(k + 1) % n];
+            let e_prev = edge_index[&Edge::new(prev, cur)];
+            let e_next = edge_index[&Edge::new(cur, next)];
+            out_faces.push(Quad::new(cur, e_next, face_offset + fi, e_prev));
+        }
+    }
+
+    (new_verts, out_faces)
+}
+
+/// Refines a generator's mesh with Catmull-Clark subdivision.
+///
+/// Implemented for any generator that can hand back its vertex buffer
+/// (`SharedVertex`) and its face indices (`IndexedPolygon`), so it works
+/// directly on top of `Cube`, `Cylinder`, or anything else built the same
+/// way.
+pub trait Subdivide<P> {
+    /// Applies `levels` rounds of Catmull-Clark subdivision and returns
+    /// the resulting all-quad mesh as a flat list of `Quad<Vertex>`.
+    fn subdivide(&self, levels: usize) -> Vec<Quad<Vertex>>;
+}
+
+impl<G, P> Subdivide<P> for G
+    where G: SharedVertex<Vertex> + IndexedPolygon<P>,
+          P: Corners
+{
+    fn subdivide(&self, levels: usize) -> Vec<Quad<Vertex>> {
+        let mut verts: Vec<Vertex> = (0..self.shared_vertex_count())
+            .map(|i| self.shared_vertex(i))
+            .collect();
+        let mut faces: Vec<Vec<usize>> = (0..self.indexed_polygon_count())
+            .map(|i| self.indexed_polygon(i).corners())
+            .collect();
+
+        let (welded_verts, welded_faces) = weld(&verts, &faces);
+        verts = welded_verts;
+        faces = welded_faces;
+
+        for _ in 0..levels {
+            let (new_verts, new_quads) = catmull_clark(&verts, &faces);
+            verts = new_verts;
+            faces = new_quads
+                .iter()
+                .map(|q| vec![q.x, q.y, q.z, q.w])
+                .collect();
+        }
+
+        faces
+            .iter()
+            .map(|f| Quad::new(verts[f[0]], verts[f[1]], verts[f[2]], verts[f[3]]))
+            .collect()
+    }
+}
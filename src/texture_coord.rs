@@ -52,4 +52,92 @@ impl UVCircle {
         [self.offset[0] + u.cos() * self.radius,
          self.offset[1] + u.sin() * self.radius]
     }
+}
+
+/// A shelf/skyline rectangle packer that lays out a set of face islands
+/// into the unit square without overlap, so a generator can request N
+/// islands of arbitrary size instead of hand-placing a bespoke layout
+/// (compare the magic constants in `Cube::uv` or `Cylinder`'s
+/// `UV_GAP`/`UV_TOP_CENTER`).
+pub struct UvAtlas;
+
+struct Shelf {
+    y: f32,
+    height: f32,
+    width_used: f32,
+}
+
+impl UvAtlas {
+    /// Packs `islands` (each a `[width, height]` in arbitrary units) into
+    /// the unit square, padding every island by `gutter` on each side to
+    /// avoid bleeding between neighbouring faces, and returns one
+    /// `UVRect` per island in the same order as `islands`.
+    ///
+    /// Islands are placed tallest-first onto horizontal shelves: each
+    /// goes on the first shelf with enough remaining width, and a new
+    /// shelf is opened when none fits. The packed extent is then used to
+    /// normalize every rectangle back down into `0.0..1.0`.
+    pub fn pack(islands: &[[f32; 2]], gutter: f32) -> Vec<UVRect> {
+        if islands.is_empty() {
+            return vec![];
+        }
+
+        let padded: Vec<[f32; 2]> = islands
+            .iter()
+            .map(|&[w, h]| [w + gutter * 2., h + gutter * 2.])
+            .collect();
+
+        let widest = padded.iter().fold(0_f32, |m, p| m.max(p[0]));
+        let total_area: f32 = padded.iter().map(|p| p[0] * p[1]).sum();
+        let sheet_width = total_area.sqrt().max(widest);
+
+        let mut order: Vec<usize> = (0..islands.len()).collect();
+        order.sort_by(|&a, &b| {
+            padded[b][1]
+                .partial_cmp(&padded[a][1])
+                .unwrap_or(::std::cmp::Ordering::Equal)
+        });
+
+        let mut shelves: Vec<Shelf> = Vec::new();
+        let mut placed = vec![[0_f32; 2]; islands.len()];
+        let mut max_width = 0_f32;
+
+        for idx in order {
+            let [w, h] = padded[idx];
+
+            let shelf_idx = shelves
+                .iter()
+                .position(|s| s.height >= h && s.width_used + w <= sheet_width);
+
+            let shelf_idx = match shelf_idx {
+                Some(i) => i,
+                None => {
+                    let y = shelves.last().map_or(0., |s| s.y + s.height);
+                    shelves.push(Shelf {
+                                     y,
+                                     height: h,
+                                     width_used: 0.,
+                                 });
+                    shelves.len() - 1
+                }
+            };
+
+            let shelf = &mut shelves[shelf_idx];
+            placed[idx] = [shelf.width_used, shelf.y];
+            shelf.width_used += w;
+            max_width = max_width.max(shelf.width_used);
+        }
+
+        let total_height = shelves.last().map_or(0., |s| s.y + s.height);
+
+        islands
+            .iter()
+            .enumerate()
+            .map(|(idx, _)| {
+                let [x, y] = placed[idx];
+                UVRect::new([(x + gutter) / max_width, (y + gutter) / total_height],
+                            [islands[idx][0] / max_width, islands[idx][1] / total_height])
+            })
+            .collect()
+    }
 }
\ No newline at end of file